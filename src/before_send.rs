@@ -1,6 +1,6 @@
 //! Implementation details for [`Options::set_before_send`].
 
-use crate::{ffi, Value};
+use crate::{ffi, stacktrace, Value};
 #[cfg(doc)]
 use crate::{Event, Options};
 use once_cell::sync::OnceCell;
@@ -90,9 +90,18 @@ pub extern "C" fn sentry_contrib_native_before_send(
         ManuallyDrop::new(unsafe { Box::<Box<dyn BeforeSend>>::from_raw(closure as _) });
 
     ffi::catch(|| {
-        before_send
-            .before_send(unsafe { Value::from_raw(event) })
-            .into_raw()
+        let mut event = unsafe { Value::from_raw(event) };
+
+        // Attach a resolved Rust stacktrace, if backtraces are enabled, to
+        // the event before the user's `BeforeSend` sees it. This covers both
+        // panics reported through `set_hook` and manual `Event` captures, as
+        // long as a `BeforeSend` is registered; events sent without one don't
+        // pass through here and get no stacktrace.
+        if let Some(stacktrace) = stacktrace::capture() {
+            event.insert("stacktrace", stacktrace);
+        }
+
+        before_send.before_send(event).into_raw()
     })
 }
 
@@ -13,15 +13,79 @@ use std::{
 };
 
 #[cfg(not(windows))]
-use std::{mem, os::unix::ffi::OsStringExt};
+use std::{
+    mem,
+    os::unix::ffi::{OsStrExt, OsStringExt},
+};
 
-/// Cross-platform return type for [`CPath::into_os_vec`].
+/// Cross-platform element type returned by [`CPath::into_os_vec`] and passed
+/// to the closure of [`CPath::with_os_path`].
 #[cfg(windows)]
 type COsString = u16;
-/// Cross-platform return type for [`CPath::into_os_vec`].
+/// Cross-platform element type returned by [`CPath::into_os_vec`] and passed
+/// to the closure of [`CPath::with_os_path`].
 #[cfg(not(windows))]
 type COsString = c_char;
 
+/// Size, in elements and including the trailing null terminator, of the
+/// fixed-size stack buffer [`RToC::with_cstr`] and [`CPath::with_os_path`]
+/// use instead of falling back to a heap allocation; inputs of up to
+/// `MAX_STACK_ALLOCATION - 1` elements fit.
+///
+/// [`RToC::into_cstring`] and [`CPath::into_os_vec`] always allocate because
+/// the caller needs an owned, outliving value; `with_cstr`/`with_os_path`
+/// exist precisely for the call sites that only need a borrow for the
+/// duration of an FFI call, so it's worth sizing this generously enough to
+/// cover that without falling back to the heap in the common case.
+const MAX_STACK_ALLOCATION: usize = 384;
+
+/// Error returned by the fallible FFI conversions: [`CPath::try_into_os_vec`],
+/// [`RToC::try_into_cstring`] and [`CToR::try_as_str`].
+///
+/// Match on this instead of calling the panicking equivalents when a value
+/// didn't originate in this process (see [`catch`] below for what happens to
+/// a panic that does slip past this boundary into `sentry-native`).
+///
+/// # Follow-up
+/// `Map`/`Value`'s own setters still call the panicking conversions
+/// internally; `tests/res/panic.rs` shows the pattern callers can use today
+/// by validating a value with these `try_*` methods before handing it to a
+/// setter, but the setters themselves don't expose a fallible overload yet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConvertError {
+    /// The value contained a null byte where none was expected.
+    NullByte,
+    /// The value wasn't valid UTF-8.
+    InvalidUtf8,
+}
+
+impl std::fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NullByte => f.write_str("found null byte"),
+            Self::InvalidUtf8 => f.write_str("found invalid UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+/// Re-encodes a [`PathBuf`] into an OS compatible `Vec<COsString>`, without
+/// the trailing null terminator.
+fn encode_os_path(path: PathBuf) -> Vec<COsString> {
+    #[cfg(windows)]
+    let path: Vec<_> = path.into_os_string().encode_wide().collect();
+    #[cfg(not(windows))]
+    let path: Vec<_> = path
+        .into_os_string()
+        .into_vec()
+        .into_iter()
+        .map(|ch| unsafe { mem::transmute::<u8, i8>(ch) })
+        .collect();
+
+    path
+}
+
 /// Helper trait to convert [`PathBuf`] to `Vec<COsString>`.
 pub trait CPath {
     /// Re-encodes `self` into an OS compatible `Vec<COsString>`.
@@ -29,26 +93,76 @@ pub trait CPath {
     /// # Panics
     /// Panics if `self` contains any null bytes.
     fn into_os_vec(self) -> Vec<COsString>;
+
+    /// Re-encodes `self` into an OS compatible `Vec<COsString>`.
+    ///
+    /// # Errors
+    /// Fails with [`ConvertError::NullByte`] if `self` contains any null
+    /// bytes, instead of panicking like [`CPath::into_os_vec`].
+    fn try_into_os_vec(self) -> Result<Vec<COsString>, ConvertError>;
+
+    /// Re-encodes `self` and passes it to `fun` as a null-terminated OS
+    /// compatible `&[COsString]`, using a buffer on the stack instead of
+    /// heap-allocating when `self` is short enough to fit.
+    ///
+    /// # Panics
+    /// Panics if `self` contains any null bytes.
+    fn with_os_path<R>(self, fun: impl FnOnce(&[COsString]) -> R) -> R;
 }
 
 impl CPath for PathBuf {
     fn into_os_vec(self) -> Vec<COsString> {
+        self.try_into_os_vec().expect("found null byte")
+    }
+
+    fn try_into_os_vec(self) -> Result<Vec<COsString>, ConvertError> {
+        let mut path = encode_os_path(self);
+
+        if path.contains(&0) {
+            return Err(ConvertError::NullByte);
+        }
+
+        path.push(0);
+        Ok(path)
+    }
+
+    fn with_os_path<R>(self, fun: impl FnOnce(&[COsString]) -> R) -> R {
+        let os_string = self.into_os_string();
+
+        // The length can be determined up-front without allocating, so the
+        // stack-buffer branch below never has to touch the heap.
         #[cfg(windows)]
-        let path: Vec<_> = self.into_os_string().encode_wide().chain(Some(0)).collect();
+        let len = os_string.encode_wide().take(MAX_STACK_ALLOCATION).count();
         #[cfg(not(windows))]
-        let path: Vec<_> = self
-            .into_os_string()
-            .into_vec()
-            .into_iter()
-            .map(|ch| unsafe { mem::transmute::<u8, i8>(ch) })
-            .chain(Some(0))
-            .collect();
-
-        if path[0..path.len() - 1].contains(&0) {
-            panic!("found null byte")
-        }
+        let len = os_string.as_bytes().len();
+
+        if len < MAX_STACK_ALLOCATION {
+            let mut buffer = [0 as COsString; MAX_STACK_ALLOCATION];
+
+            #[cfg(windows)]
+            for (slot, ch) in buffer.iter_mut().zip(os_string.encode_wide()) {
+                *slot = ch;
+            }
+            #[cfg(not(windows))]
+            for (slot, &byte) in buffer.iter_mut().zip(os_string.as_bytes()) {
+                *slot = unsafe { mem::transmute::<u8, i8>(byte) };
+            }
+
+            if buffer[..len].contains(&0) {
+                panic!("found null byte")
+            }
 
-        path
+            fun(&buffer[..=len])
+        } else {
+            let mut path = encode_os_path(PathBuf::from(os_string));
+
+            if path.contains(&0) {
+                panic!("found null byte")
+            }
+
+            path.push(0);
+            fun(&path)
+        }
     }
 }
 
@@ -63,18 +177,30 @@ pub trait CToR {
     /// The same safety issues apply as in [`CStr::from_ptr`], except the null
     /// pointer check, but the main concern is the lifetime of the pointer.
     unsafe fn as_str<'a>(self) -> Option<&'a str>;
+
+    /// Yields a [`str`] from `self`, or `None` if `self` is a null pointer.
+    ///
+    /// # Safety
+    /// The same safety issues apply as in [`CStr::from_ptr`], except the null
+    /// pointer check, but the main concern is the lifetime of the pointer.
+    unsafe fn try_as_str<'a>(self) -> Option<Result<&'a str, ConvertError>>;
 }
 
 impl CToR for *const c_char {
     #[allow(unused_unsafe)]
     unsafe fn as_str<'a>(self) -> Option<&'a str> {
+        unsafe { self.try_as_str() }.map(|result| result.expect("found invalid UTF-8"))
+    }
+
+    #[allow(unused_unsafe)]
+    unsafe fn try_as_str<'a>(self) -> Option<Result<&'a str, ConvertError>> {
         if self.is_null() {
             None
         } else {
             Some(
                 unsafe { CStr::from_ptr(self) }
                     .to_str()
-                    .expect("found invalid UTF-8"),
+                    .map_err(|_| ConvertError::InvalidUtf8),
             )
         }
     }
@@ -87,11 +213,45 @@ pub trait RToC {
     /// # Panics
     /// Panics if `self` contains any null bytes.
     fn into_cstring(self) -> CString;
+
+    /// Re-encodes `self` into a [`CString`].
+    ///
+    /// # Errors
+    /// Fails with [`ConvertError::NullByte`] if `self` contains any null
+    /// bytes, instead of panicking like [`RToC::into_cstring`].
+    fn try_into_cstring(self) -> Result<CString, ConvertError>;
+
+    /// Passes `self` to `fun` as a [`&CStr`](CStr), using a buffer on the
+    /// stack instead of heap-allocating when `self` is short enough to fit.
+    ///
+    /// # Panics
+    /// Panics if `self` contains any null bytes.
+    fn with_cstr<R>(self, fun: impl FnOnce(&CStr) -> R) -> R;
 }
 
 impl RToC for String {
     fn into_cstring(self) -> CString {
-        CString::new(self).expect("found null byte")
+        self.try_into_cstring().expect("found null byte")
+    }
+
+    fn try_into_cstring(self) -> Result<CString, ConvertError> {
+        CString::new(self).map_err(|_| ConvertError::NullByte)
+    }
+
+    fn with_cstr<R>(self, fun: impl FnOnce(&CStr) -> R) -> R {
+        let bytes = self.into_bytes();
+
+        if bytes.contains(&0) {
+            panic!("found null byte")
+        }
+
+        if bytes.len() < MAX_STACK_ALLOCATION {
+            let mut buffer = [0_u8; MAX_STACK_ALLOCATION];
+            buffer[..bytes.len()].copy_from_slice(&bytes);
+            fun(unsafe { CStr::from_bytes_with_nul_unchecked(&buffer[..=bytes.len()]) })
+        } else {
+            fun(&CString::new(bytes).expect("found null byte"))
+        }
     }
 }
 
@@ -154,6 +314,78 @@ mod cpath {
     invalid!(invalid_4, convert("\0🤦‍♂️🤦‍♀️🤷‍♂️🤷‍♀️"));
     invalid!(invalid_5, convert("abcd\0efgh"));
     invalid!(invalid_6, convert("🤦‍♂️🤦‍♀️\0🤷‍♂️🤷‍♀️"));
+
+    fn convert_with(string: &str) -> OsString {
+        PathBuf::from(string.to_owned()).with_os_path(|path| {
+            #[cfg(windows)]
+            {
+                OsString::from_wide(path)
+            }
+            #[cfg(not(windows))]
+            {
+                OsString::from_vec(
+                    path.iter()
+                        .map(|&ch| unsafe { mem::transmute::<i8, u8>(ch) })
+                        .collect(),
+                )
+            }
+        })
+    }
+
+    #[test]
+    fn valid_with() {
+        assert_eq!("abcdefgh\0", convert_with("abcdefgh"));
+        assert_eq!("🤦‍♂️🤦‍♀️🤷‍♂️🤷‍♀️\0", convert_with("🤦‍♂️🤦‍♀️🤷‍♂️🤷‍♀️"));
+    }
+
+    #[test]
+    fn valid_with_around_stack_buffer() {
+        for len in super::MAX_STACK_ALLOCATION - 1..=super::MAX_STACK_ALLOCATION + 1 {
+            let string = "a".repeat(len);
+            let mut expected = string.clone();
+            expected.push('\0');
+            assert_eq!(expected, convert_with(&string));
+        }
+    }
+
+    invalid!(invalid_with_1, convert_with("abcdefgh\0"));
+    invalid!(invalid_with_2, convert_with("🤦‍♂️🤦‍♀️🤷‍♂️🤷‍♀️\0"));
+    invalid!(invalid_with_3, convert_with("\0abcdefgh"));
+    invalid!(invalid_with_4, convert_with("\0🤦‍♂️🤦‍♀️🤷‍♂️🤷‍♀️"));
+    invalid!(invalid_with_5, convert_with("abcd\0efgh"));
+    invalid!(invalid_with_6, convert_with("🤦‍♂️🤦‍♀️\0🤷‍♂️🤷‍♀️"));
+
+    fn try_convert(string: &str) -> Result<OsString, super::ConvertError> {
+        PathBuf::from(string.to_owned())
+            .try_into_os_vec()
+            .map(|path| {
+                #[cfg(windows)]
+                {
+                    OsString::from_wide(&path[..])
+                }
+                #[cfg(not(windows))]
+                {
+                    OsString::from_vec(
+                        path.into_iter()
+                            .map(|ch| unsafe { mem::transmute::<i8, u8>(ch) })
+                            .collect(),
+                    )
+                }
+            })
+    }
+
+    #[test]
+    fn try_valid() {
+        assert_eq!("abcdefgh\0", try_convert("abcdefgh").unwrap());
+    }
+
+    #[test]
+    fn try_invalid() {
+        assert_eq!(
+            super::ConvertError::NullByte,
+            try_convert("abcd\0efgh").unwrap_err(),
+        );
+    }
 }
 
 #[cfg(test)]
@@ -180,6 +412,23 @@ mod ctor {
         let string = CString::new(vec![0xfe, 0xfe, 0xff, 0xff]).unwrap();
         unsafe { string.as_ptr().as_str() };
     });
+
+    #[test]
+    fn try_valid() {
+        let string = CString::new("abcdefgh").unwrap();
+        assert_eq!(Some(Ok("abcdefgh")), unsafe {
+            string.as_ptr().try_as_str()
+        });
+        assert_eq!(None, unsafe { ptr::null::<c_char>().try_as_str() });
+    }
+
+    #[test]
+    fn try_invalid() {
+        let string = CString::new(vec![0xfe, 0xfe, 0xff, 0xff]).unwrap();
+        assert_eq!(Some(Err(super::ConvertError::InvalidUtf8)), unsafe {
+            string.as_ptr().try_as_str()
+        },);
+    }
 }
 
 #[cfg(test)]
@@ -209,6 +458,53 @@ mod rtoc {
     invalid!(invalid_4, convert("\0🤦‍♂️🤦‍♀️🤷‍♂️🤷‍♀️"));
     invalid!(invalid_5, convert("abcd\0efgh"));
     invalid!(invalid_6, convert("🤦‍♂️🤦‍♀️\0🤷‍♂️🤷‍♀️"));
+
+    fn convert_with(string: &str) -> String {
+        string
+            .to_owned()
+            .with_cstr(|string| string.to_str().unwrap().to_owned())
+    }
+
+    #[test]
+    fn valid_with() {
+        assert_eq!("abcdefgh", convert_with("abcdefgh"));
+        assert_eq!("🤦‍♂️🤦‍♀️🤷‍♂️🤷‍♀️", convert_with("🤦‍♂️🤦‍♀️🤷‍♂️🤷‍♀️"));
+    }
+
+    #[test]
+    fn valid_with_around_stack_buffer() {
+        for len in super::MAX_STACK_ALLOCATION - 1..=super::MAX_STACK_ALLOCATION + 1 {
+            let string = "a".repeat(len);
+            assert_eq!(string, convert_with(&string));
+        }
+    }
+
+    invalid!(invalid_with_1, convert_with("abcdefgh\0"));
+    invalid!(invalid_with_2, convert_with("🤦‍♂️🤦‍♀️🤷‍♂️🤷‍♀️\0"));
+    invalid!(invalid_with_3, convert_with("\0abcdefgh"));
+    invalid!(invalid_with_4, convert_with("\0🤦‍♂️🤦‍♀️🤷‍♂️🤷‍♀️"));
+    invalid!(invalid_with_5, convert_with("abcd\0efgh"));
+    invalid!(invalid_with_6, convert_with("🤦‍♂️🤦‍♀️\0🤷‍♂️🤷‍♀️"));
+
+    fn try_convert(string: &str) -> Result<String, super::ConvertError> {
+        string
+            .to_owned()
+            .try_into_cstring()
+            .map(|string| string.to_str().unwrap().to_owned())
+    }
+
+    #[test]
+    fn try_valid() {
+        assert_eq!("abcdefgh", try_convert("abcdefgh").unwrap());
+    }
+
+    #[test]
+    fn try_invalid() {
+        assert_eq!(
+            super::ConvertError::NullByte,
+            try_convert("abcd\0efgh").unwrap_err(),
+        );
+    }
 }
 
 #[cfg(test)]
@@ -216,4 +512,4 @@ mod rtoc {
 #[should_panic]
 fn catch_panic() {
     catch(|| panic!("test"))
-}
\ No newline at end of file
+}
@@ -0,0 +1,162 @@
+//! Resolves the current Rust call stack into a structured Sentry
+//! [`stacktrace`](https://develop.sentry.dev/sdk/event-payloads/stacktrace/),
+//! so panics reported through [`set_hook`] show readable, grouped frames in
+//! the Sentry UI instead of only the raw addresses `sentry-native` itself
+//! recovers.
+
+#[cfg(doc)]
+use crate::set_hook;
+use crate::{Map, Value};
+use std::env;
+
+/// Prefixes of resolved function paths that belong to the Rust standard
+/// library or panic runtime rather than user code.
+///
+/// Frames matching one of these are reported with `in_app: false`, so
+/// Sentry's UI collapses them by default and leaves user frames front and
+/// centre.
+const RUNTIME_PREFIXES: &[&str] = &[
+    "std::",
+    "core::",
+    "alloc::",
+    "rust_begin_unwind",
+    "_rust_begin_unwind",
+    "__rust_begin_short_backtrace",
+];
+
+/// Whether `RUST_LIB_BACKTRACE`/`RUST_BACKTRACE` ask for backtraces to be
+/// captured, mirroring the precedence the standard library itself uses to
+/// decide whether `Backtrace::capture` does any work.
+///
+/// Checking this directly, rather than capturing a throwaway
+/// [`Backtrace`](std::backtrace::Backtrace) just to read its
+/// [`status`](std::backtrace::Backtrace::status), avoids paying for a stack
+/// walk twice: once for the throwaway capture and again in [`capture`]'s own
+/// `backtrace::trace` below.
+fn backtraces_requested() -> bool {
+    let var = env::var("RUST_LIB_BACKTRACE").or_else(|_| env::var("RUST_BACKTRACE"));
+    !matches!(var.as_deref(), Ok("0") | Err(_))
+}
+
+/// Captures the current call stack and returns it as a Sentry
+/// [`stacktrace`](https://develop.sentry.dev/sdk/event-payloads/stacktrace/)
+/// object [`Value`] (to be inserted under a `"stacktrace"` key), or [`None`]
+/// if backtraces aren't enabled.
+///
+/// # Notes
+/// Merged into outgoing events in
+/// [`sentry_contrib_native_before_send`](crate::before_send::sentry_contrib_native_before_send),
+/// which runs for both panics reported through [`set_hook`] and manual
+/// [`Event`](crate::Event) captures, but only when a
+/// [`BeforeSend`](crate::BeforeSend) is registered; other events get no
+/// stacktrace.
+pub fn capture() -> Option<Value> {
+    if !backtraces_requested() {
+        return None;
+    }
+
+    let mut frames = Vec::new();
+
+    backtrace::trace(|frame| {
+        backtrace::resolve_frame(frame, |symbol| {
+            let mut map = Map::new();
+
+            let function = symbol
+                .name()
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| "<unknown>".to_owned());
+
+            map.insert("in_app", !is_runtime_frame(&function));
+            map.insert("function", function);
+
+            if let Some(filename) = symbol.filename() {
+                map.insert("filename", filename.to_string_lossy().into_owned());
+            }
+
+            if let Some(lineno) = symbol.lineno() {
+                map.insert("lineno", lineno);
+            }
+
+            if let Some(colno) = symbol.colno() {
+                map.insert("colno", colno);
+            }
+
+            map.insert("instruction_addr", format!("{:?}", frame.ip()));
+
+            frames.push(Value::from(map));
+        });
+
+        true
+    });
+
+    // `backtrace::trace` walks the stack innermost-frame-first, but Sentry
+    // expects the oldest call first.
+    frames.reverse();
+
+    let mut stacktrace = Map::new();
+    stacktrace.insert("frames", frames);
+
+    Some(Value::from(stacktrace))
+}
+
+/// Whether `function` belongs to the Rust standard library or panic runtime
+/// rather than user code.
+fn is_runtime_frame(function: &str) -> bool {
+    RUNTIME_PREFIXES
+        .iter()
+        .any(|prefix| function.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod test {
+    use super::is_runtime_frame;
+
+    #[test]
+    fn runtime_frames() {
+        assert!(is_runtime_frame("std::panicking::begin_panic"));
+        assert!(is_runtime_frame("core::option::Option<T>::unwrap"));
+        assert!(is_runtime_frame("rust_begin_unwind"));
+    }
+
+    #[test]
+    fn user_frames() {
+        assert!(!is_runtime_frame("my_crate::module::function"));
+        assert!(!is_runtime_frame(
+            "sentry_contrib_native::stacktrace::capture"
+        ));
+    }
+}
+
+#[cfg(test)]
+mod backtraces_requested {
+    use super::backtraces_requested;
+    use std::env;
+
+    #[rusty_fork::test_fork]
+    fn unset() {
+        env::remove_var("RUST_BACKTRACE");
+        env::remove_var("RUST_LIB_BACKTRACE");
+        assert!(!backtraces_requested());
+    }
+
+    #[rusty_fork::test_fork]
+    fn disabled() {
+        env::set_var("RUST_BACKTRACE", "0");
+        env::remove_var("RUST_LIB_BACKTRACE");
+        assert!(!backtraces_requested());
+    }
+
+    #[rusty_fork::test_fork]
+    fn enabled() {
+        env::set_var("RUST_BACKTRACE", "1");
+        env::remove_var("RUST_LIB_BACKTRACE");
+        assert!(backtraces_requested());
+    }
+
+    #[rusty_fork::test_fork]
+    fn lib_backtrace_takes_precedence() {
+        env::set_var("RUST_BACKTRACE", "0");
+        env::set_var("RUST_LIB_BACKTRACE", "1");
+        assert!(backtraces_requested());
+    }
+}
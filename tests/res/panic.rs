@@ -1,5 +1,5 @@
 use anyhow::Result;
-use sentry::{Options, User};
+use sentry::{Options, RToC, User};
 use sentry_contrib_native as sentry;
 use std::io::{self, Read};
 
@@ -23,9 +23,17 @@ fn main() -> Result<()> {
     io::stdin().read_exact(&mut buffer)?;
     let id = hex::encode(buffer);
 
-    let mut user = User::new();
-    user.insert("id", id);
-    user.set();
+    // `id` comes from outside input, so validate it instead of letting
+    // `User::insert` panic (and abort, since that panic would unwind
+    // straight into `sentry-native`) on a bad value.
+    match id.clone().try_into_cstring() {
+        Ok(_) => {
+            let mut user = User::new();
+            user.insert("id", id);
+            user.set();
+        }
+        Err(err) => eprintln!("ignoring invalid user id: {}", err),
+    }
 
     panic!("test panic")
 }